@@ -0,0 +1,88 @@
+use super::ir::{self, Ir};
+use super::Ast;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+  IncPtr(usize),
+  DecPtr(usize),
+  IncVal(u8),
+  DecVal(u8),
+  Output,
+  Input,
+  JumpIfZero(usize),
+  JumpIfNonZero(usize),
+  SetVal(u8),
+  MulAdd { offset: isize, factor: i8 },
+}
+
+pub struct Compiler {
+  code: Vec<Op>,
+}
+
+impl Compiler {
+  pub fn new() -> Self {
+    Self {
+      code: vec![],
+    }
+  }
+
+  /// Optimizes `ast` into IR (coalescing operator runs, recognizing
+  /// clear/multiply loops) and lowers the result into flat bytecode with
+  /// jump targets resolved to absolute instruction indices.
+  pub fn compile(ast: &Ast) -> Vec<Op> {
+    let mut compiler = Compiler::new();
+    compiler.compile_ir(&ir::optimize(ast));
+    compiler.code
+  }
+
+  fn compile_ir(&mut self, nodes: &[Ir]) {
+    for node in nodes.iter() {
+      match node {
+        Ir::AddVal(delta) => self.push_add_val(*delta),
+        Ir::MovePtr(delta) => self.push_move_ptr(*delta),
+        Ir::Output => self.code.push(Op::Output),
+        Ir::Input => self.code.push(Op::Input),
+        Ir::SetVal(value) => self.code.push(Op::SetVal(*value)),
+        Ir::MulAdd { offset, factor } => self.code.push(Op::MulAdd { offset: *offset, factor: *factor }),
+        Ir::Loop(body) => self.compile_loop(body),
+      }
+    }
+  }
+
+  fn push_add_val(&mut self, delta: i8) {
+    if delta >= 0 {
+      self.code.push(Op::IncVal(delta as u8));
+    } else {
+      self.code.push(Op::DecVal(delta.unsigned_abs()));
+    }
+  }
+
+  fn push_move_ptr(&mut self, delta: isize) {
+    if delta >= 0 {
+      self.code.push(Op::IncPtr(delta as usize));
+    } else {
+      self.code.push(Op::DecPtr(delta.unsigned_abs()));
+    }
+  }
+
+  fn compile_loop(&mut self, body: &[Ir]) {
+    let jump_if_zero = self.code.len();
+    self.code.push(Op::JumpIfZero(0));
+
+    self.compile_ir(body);
+
+    let jump_if_non_zero = self.code.len();
+    self.code.push(Op::JumpIfNonZero(0));
+
+    // Backpatch now that both targets are known: `[` lands just past the
+    // matching `]`, and `]` loops back to just past the matching `[`.
+    self.code[jump_if_zero] = Op::JumpIfZero(jump_if_non_zero + 1);
+    self.code[jump_if_non_zero] = Op::JumpIfNonZero(jump_if_zero + 1);
+  }
+}
+
+impl Default for Compiler {
+  fn default() -> Self {
+    Self::new()
+  }
+}
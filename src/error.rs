@@ -0,0 +1,12 @@
+use thiserror::Error;
+
+/// Errors surfaced while parsing a Brainfuck program, in place of the panics
+/// that used to greet an unbalanced program with a backtrace instead of a
+/// diagnostic.
+#[derive(Error, Debug)]
+pub enum BfError {
+  #[error("unmatched '[' at {line}:{col}")]
+  UnmatchedLoopStart { line: usize, col: usize },
+  #[error("unmatched ']' at {line}:{col}")]
+  UnmatchedLoopEnd { line: usize, col: usize },
+}
@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+
+use super::{Ast, Node, NodeType};
+
+/// Intermediate representation sitting between the parsed `Ast` and bytecode
+/// codegen. `optimize` lowers an `Ast` into this form while rewriting common
+/// Brainfuck idioms (operator runs, clear loops, copy/multiply loops) so the
+/// compiler never has to emit a real loop for them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Ir {
+  AddVal(i8),
+  MovePtr(isize),
+  Output,
+  Input,
+  SetVal(u8),
+  MulAdd { offset: isize, factor: i8 },
+  Loop(Vec<Ir>),
+}
+
+pub fn optimize(ast: &Ast) -> Vec<Ir> {
+  optimize_body(lower(&ast.body))
+}
+
+fn lower(nodes: &[Node]) -> Vec<Ir> {
+  let mut out = vec![];
+
+  for node in nodes.iter() {
+    match node.kind {
+      NodeType::Ignore | NodeType::WhiteSpace | NodeType::EOF => {},
+      NodeType::CellIncrement => out.push(Ir::AddVal(1)),
+      NodeType::CellDecrement => out.push(Ir::AddVal(-1)),
+      NodeType::PointerIncrement => out.push(Ir::MovePtr(1)),
+      NodeType::PointerDecrement => out.push(Ir::MovePtr(-1)),
+      NodeType::Output => out.push(Ir::Output),
+      NodeType::Input => out.push(Ir::Input),
+      NodeType::LoopStart => out.push(Ir::Loop(lower(&node.body.as_ref().unwrap().body))),
+    }
+  }
+
+  out
+}
+
+fn optimize_body(nodes: Vec<Ir>) -> Vec<Ir> {
+  let nodes = coalesce_runs(nodes);
+  let mut out = vec![];
+
+  for node in nodes {
+    match node {
+      Ir::Loop(body) => out.extend(optimize_loop(optimize_body(body))),
+      other => out.push(other),
+    }
+  }
+
+  out
+}
+
+/// Collapses consecutive `AddVal`/`MovePtr` into one, dropping any run that
+/// nets to zero. `AddVal` runs use wrapping `i8` arithmetic, which matches the
+/// wrapping `u8` cells the ops are eventually applied to. `MovePtr` runs are
+/// summed into their net movement, so only where the pointer ends up is
+/// checked against the tape bounds, not the positions it passes on the way.
+fn coalesce_runs(nodes: Vec<Ir>) -> Vec<Ir> {
+  let mut out: Vec<Ir> = vec![];
+
+  for node in nodes {
+    match (out.last_mut(), &node) {
+      (Some(Ir::AddVal(a)), Ir::AddVal(b)) => *a = a.wrapping_add(*b),
+      (Some(Ir::MovePtr(a)), Ir::MovePtr(b)) => *a += b,
+      _ => out.push(node),
+    }
+  }
+
+  out.into_iter().filter(|ir| !matches!(ir, Ir::AddVal(0) | Ir::MovePtr(0))).collect()
+}
+
+/// Recognizes `[-]`/`[+]` clear loops and balanced copy/multiply loops on an
+/// already-optimized loop body, falling back to a plain `Ir::Loop` otherwise.
+fn optimize_loop(body: Vec<Ir>) -> Vec<Ir> {
+  if let [Ir::AddVal(1 | -1)] = body.as_slice() {
+    return vec![Ir::SetVal(0)];
+  }
+
+  if let Some(mut ops) = recognize_multiply_loop(&body) {
+    ops.push(Ir::SetVal(0));
+    return ops;
+  }
+
+  vec![Ir::Loop(body)]
+}
+
+/// A copy/multiply loop is balanced (net pointer movement zero), decrements
+/// the origin cell by exactly one, and otherwise only adds constants to
+/// other offsets. Anything else (I/O, nested loops, a non-`-1` origin delta)
+/// disqualifies the pattern.
+fn recognize_multiply_loop(body: &Vec<Ir>) -> Option<Vec<Ir>> {
+  let mut offset: isize = 0;
+  let mut deltas: BTreeMap<isize, i32> = BTreeMap::new();
+
+  for ir in body {
+    match ir {
+      Ir::MovePtr(n) => offset += n,
+      Ir::AddVal(n) => *deltas.entry(offset).or_insert(0) += *n as i32,
+      _ => return None,
+    }
+  }
+
+  if offset != 0 || deltas.get(&0).copied().unwrap_or(0) != -1 {
+    return None;
+  }
+
+  let mut ops = vec![];
+  for (&off, &delta) in deltas.iter() {
+    if off == 0 || delta == 0 {
+      continue;
+    }
+    if delta < i8::MIN as i32 || delta > i8::MAX as i32 {
+      return None;
+    }
+    ops.push(Ir::MulAdd { offset: off, factor: delta as i8 });
+  }
+
+  Some(ops)
+}
@@ -1,7 +1,25 @@
-use std::{io::{self, Read}, char};
+use std::{fmt, io};
 
 use wasm_bindgen::prelude::*;
 
+// `main.rs` pulls this file in via `mod lib;` rather than linking the
+// compiled library crate, so these need explicit paths: without them rustc
+// would resolve `compiler`/`error`/`ir`/`vm` relative to a `lib` module
+// directory (`src/lib/...`) instead of the sibling files that are actually
+// there.
+#[path = "compiler.rs"]
+pub mod compiler;
+#[path = "error.rs"]
+pub mod error;
+#[path = "ir.rs"]
+pub mod ir;
+#[path = "vm.rs"]
+pub mod vm;
+
+pub use compiler::{Compiler, Op};
+pub use error::BfError;
+pub use vm::{EofBehavior, TapeOverflow, Vm, VmLimits};
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TokenType {
   IncrementPointer,
@@ -17,9 +35,18 @@ pub enum TokenType {
   EOF,
 }
 
+/// A source location tracked as the `Lexer` scans, so errors and debug
+/// dumps can point at a real `line:col` instead of a flat character index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+  pub line: usize,
+  pub col: usize,
+  pub byte_offset: usize,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Token {
-  pub pos: usize,
+  pub span: Span,
   pub kind: TokenType,
   pub lexeme: char,
 }
@@ -36,7 +63,14 @@ impl<'a> Lexer<'a> {
   }
 
   pub fn tokenize(&mut self) -> Vec<Token> {
-    self.input.chars().enumerate().map(|(i, c)| {
+    let mut tokens = vec![];
+    let mut line = 1;
+    let mut col = 1;
+    let mut byte_offset = 0;
+
+    for c in self.input.chars() {
+      let span = Span { line, col, byte_offset };
+
       let kind = match c {
         '>' => TokenType::IncrementPointer,
         '<' => TokenType::DecrementPointer,
@@ -46,24 +80,27 @@ impl<'a> Lexer<'a> {
         ',' => TokenType::Input,
         '[' => TokenType::LoopStart,
         ']' => TokenType::LoopEnd,
-        '\0' => TokenType::EOF,
         ' ' => TokenType::WhiteSpace,
         _ => TokenType::Ignore,
       };
 
-      match kind {
-        TokenType::EOF => Token {
-          pos: i + 1,
-          kind,
-          lexeme: '\0',
-        },
-        _ => Token {
-          pos: i + 1,
-          kind,
-          lexeme: c,
-        },
+      tokens.push(Token { span, kind, lexeme: c });
+
+      byte_offset += c.len_utf8();
+      if c == '\n' {
+        line += 1;
+        col = 1;
+      } else {
+        col += 1;
       }
-    }).collect::<Vec<Token>>()   
+    }
+
+    // Always terminate with a sentinel `EOF` token: real source never
+    // contains a literal NUL, so `Parser::is_at_end`/`peek` must not rely
+    // on one showing up in the token stream on its own.
+    tokens.push(Token { span: Span { line, col, byte_offset }, kind: TokenType::EOF, lexeme: '\0' });
+
+    tokens
   }
 }
 
@@ -80,7 +117,6 @@ pub enum NodeType {
   #[allow(dead_code)]
   WhiteSpace,
   LoopStart,
-  LoopEnd,
   #[allow(dead_code)]
   EOF,
 }
@@ -97,6 +133,41 @@ pub struct Node {
   pub body: Option<Box<Ast>>,
 }
 
+/// Pretty-prints the tree indented by loop nesting, e.g.:
+/// ```text
+/// CellIncrement 1:1
+/// LoopStart 1:2
+///   PointerIncrement 1:3
+/// ```
+impl fmt::Display for Ast {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write_ast_body(f, &self.body, 0)
+  }
+}
+
+impl fmt::Display for Node {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write_ast_node(f, self, 0)
+  }
+}
+
+fn write_ast_body(f: &mut fmt::Formatter, body: &[Node], depth: usize) -> fmt::Result {
+  for node in body.iter() {
+    write_ast_node(f, node, depth)?;
+  }
+  Ok(())
+}
+
+fn write_ast_node(f: &mut fmt::Formatter, node: &Node, depth: usize) -> fmt::Result {
+  writeln!(f, "{}{:?} {}:{}", "  ".repeat(depth), node.kind, node.token.span.line, node.token.span.col)?;
+
+  if let Some(ast) = &node.body {
+    write_ast_body(f, &ast.body, depth + 1)?;
+  }
+
+  Ok(())
+}
+
 pub struct Parser {
   pub tokens: Vec<Token>,
   pub current: usize,
@@ -110,91 +181,89 @@ impl Parser {
     }
   }
 
-  pub fn parse(&mut self) -> Ast {
+  pub fn parse(&mut self) -> Result<Ast, BfError> {
     let mut ast = Ast {
       body: vec![],
     };
 
     while !self.is_at_end() {
-      ast.body.push(self.expression());
+      ast.body.push(self.expression()?);
     }
 
-    ast
+    Ok(ast)
   }
 
-  fn expression(&mut self) -> Node {
+  fn expression(&mut self) -> Result<Node, BfError> {
     let token = self.advance();
 
     match token.kind {
-      TokenType::IncrementValue => Node {
+      TokenType::IncrementValue => Ok(Node {
         token,
         kind: NodeType::CellIncrement,
         body: None,
-      },
-      TokenType::DecrementValue => Node {
+      }),
+      TokenType::DecrementValue => Ok(Node {
         token,
         kind: NodeType::CellDecrement,
         body: None,
-      },
-      TokenType::IncrementPointer => Node {
+      }),
+      TokenType::IncrementPointer => Ok(Node {
         token,
         kind: NodeType::PointerIncrement,
         body: None,
-      },
-      TokenType::DecrementPointer => Node {
+      }),
+      TokenType::DecrementPointer => Ok(Node {
         token,
         kind: NodeType::PointerDecrement,
         body: None,
-      },
-      TokenType::Output => Node {
+      }),
+      TokenType::Output => Ok(Node {
         token,
         kind: NodeType::Output,
         body: None,
-      },
-      TokenType::Input => Node {
+      }),
+      TokenType::Input => Ok(Node {
         token,
         kind: NodeType::Input,
         body: None,
-      },
+      }),
       TokenType::LoopStart => {
         let mut ast = Ast {
           body: vec![],
         };
 
         while !self.is_at_end() && self.peek().kind != TokenType::LoopEnd {
-          ast.body.push(self.expression());
+          ast.body.push(self.expression()?);
+        }
+
+        if self.is_at_end() {
+          return Err(BfError::UnmatchedLoopStart { line: token.span.line, col: token.span.col });
         }
 
         self.advance();
 
-        Node {
+        Ok(Node {
           token,
           kind: NodeType::LoopStart,
           body: Some(Box::new(ast)),
-        }
-      },
-      TokenType::LoopEnd => {
-        Node {
-          token,
-          kind: NodeType::LoopEnd,
-          body: None,
-        }
+        })
       },
+      TokenType::LoopEnd => Err(BfError::UnmatchedLoopEnd { line: token.span.line, col: token.span.col }),
       TokenType::WhiteSpace => {
-        Node {
+        Ok(Node {
           token,
           kind: NodeType::WhiteSpace,
           body: None,
-        }
+        })
       },
       TokenType::Ignore => {
-        Node {
+        Ok(Node {
           token,
           kind: NodeType::Ignore,
           body: None,
-        }
+        })
       },
-      _ => panic!("Unexpected token: {:?}", token),
+      TokenType::EOF => unreachable!("is_at_end() guards every call site against an EOF token"),
     }
   }
 
@@ -219,116 +288,25 @@ impl Parser {
   }
 }
 
-pub trait Output {
-  fn output(&mut self) -> io::Result<()>;
-}
-
-pub trait Loop {
-  fn loop_start(&mut self, nodes: &Vec<Node>) -> io::Result<()>;
-  fn loop_end(&self, nodes: &Vec<Node>);
-}
-
-pub trait Input {
-  fn input(&mut self);
-}
-
-pub struct Interpreter<'a> {
-  pub ast: Ast,
-  pub cells: Vec<u8>,
-  pub pointer: usize,
-  pub output: &'a mut dyn io::Write,
-}
-
-impl<'a> Output for Interpreter<'a> {
-  fn output(&mut self) -> io::Result<()> {
-    write!(self.output, "{}", self.cells[self.pointer] as char)?;
-    Ok(())
-  }
-}
-
-impl<'a> Input for Interpreter<'a> {
-  fn input(&mut self) {
-    let mut input: [u8; 1] = [0];
-    if let Err(error) = std::io::stdin().read_exact(&mut input) {
-      panic!("Error reading input: {}", error);
-    }
-    self.cells[self.pointer] = input[0];
-  }
-}
-
-impl<'a> Loop for Interpreter<'a> {
-  fn loop_start(&mut self, nodes: &Vec<Node>) -> io::Result<()> {
-    while self.cells[self.pointer] != 0 {
-      self.interpret(Some(&nodes))?;
-    } 
-    Ok(())
-  }
-
-  fn loop_end(&self, _nodes: &Vec<Node>) {}
-}
-
-impl<'a> Interpreter<'a> {
-  pub fn new(ast: Ast, output: &'a mut dyn io::Write) -> Self {
-    Self {
-      ast,
-      cells: vec![0; 30_000],
-      pointer: 0,
-      output,
-    }
-  }
-
-  pub fn interpret(&mut self, nodes: Option<&Vec<Node>>) -> io::Result<()> {
-    match nodes {
-      Some(body) => {
-        for node in body.iter() {
-          match node.kind {
-            NodeType::Ignore | NodeType::WhiteSpace | NodeType::LoopEnd | NodeType::EOF => {},
-            NodeType::CellIncrement => self.cells[self.pointer] += 1,
-            NodeType::CellDecrement => self.cells[self.pointer] -= 1,
-            NodeType::PointerIncrement => {
-              self.pointer += 1;
-              if self.pointer >= self.cells.len() {
-                self.pointer = 0;
-              }
-            },
-            NodeType::PointerDecrement => {
-              if self.pointer == 0 {
-                self.pointer = self.cells.len() - 1;
-              } else {
-                self.pointer -= 1;
-              }
-            },
-            NodeType::Output => {
-              self.output()?;
-            },
-            NodeType::Input => {
-              self.input();
-            },
-            NodeType::LoopStart => {
-              self.loop_start(&node.body.as_ref().unwrap().body)?;
-            },
-          }
-        }
-      },
-      None => {
-        self.interpret(Some(&self.ast.body.clone()))?;
-      },
-    }
-    Ok(())
-  }
-}
-
 #[wasm_bindgen]
 #[allow(dead_code)]
-pub fn run(code: &str) -> String {
+pub fn run(code: &str, input: &str) -> Result<String, String> {
   let mut lexer = Lexer::new(code);
   let tokens = lexer.tokenize();
   let mut parser = Parser::new(tokens);
-  let ast = parser.parse();
+  let ast = parser.parse().map_err(|error| error.to_string())?;
+  let program = Compiler::compile(&ast);
   let mut stdout: Vec<u8> = Vec::new();
-  let mut interpreter = Interpreter::new(ast, &mut stdout);
-  interpreter.interpret(None).unwrap();
-  stdout.iter().map(|&c| c as char).collect::<String>()
+  let mut stdin = io::Cursor::new(input.as_bytes());
+  let limits = VmLimits {
+    max_steps: Some(100_000_000),
+    ..VmLimits::default()
+  };
+  let mut vm = Vm::with_limits(program, &mut stdout, limits).with_input(&mut stdin);
+  // A bounded step budget means a runaway loop returns partial output
+  // instead of hanging the page; ignore the limit error either way.
+  let _ = vm.run();
+  Ok(stdout.iter().map(|&c| c as char).collect::<String>())
 }
 
 #[cfg(test)]
@@ -337,29 +315,37 @@ mod tests {
 
   #[test]
   fn test_tokenize() {
-    let mut lexer = Lexer::new("++++++++++[>++++++++>+++++++++++>++++++++++>++++>+++>++++++++>++++++++++++>+++++++++++>++++++++++>+++++++++++>+++>+<<<<<<<<<<<<-]>-.>--.>---.>++++.>++.>---.>---.>.>.>+.>+++.>.\0");
+    let mut lexer = Lexer::new("++++++++++[>++++++++>+++++++++++>++++++++++>++++>+++>++++++++>++++++++++++>+++++++++++>++++++++++>+++++++++++>+++>+<<<<<<<<<<<<-]>-.>--.>---.>++++.>++.>---.>---.>.>.>+.>+++.>.");
     let tokens = lexer.tokenize();
     assert_eq!(tokens.len(), 176);
     assert_eq!(tokens[0].kind, TokenType::IncrementValue);
     assert_eq!(tokens[0].lexeme, '+');
   }
 
+  #[test]
+  fn test_tokenize_tracks_line_and_col_across_newlines() {
+    let mut lexer = Lexer::new("+\n-");
+    let tokens = lexer.tokenize();
+    assert_eq!(tokens[0].span, Span { line: 1, col: 1, byte_offset: 0 });
+    assert_eq!(tokens[2].span, Span { line: 2, col: 1, byte_offset: 2 });
+  }
+
   #[test]
   fn test_parser() {
     let mut parser = Parser::new(vec![Token {
-      pos: 0,
+      span: Span { line: 1, col: 1, byte_offset: 0 },
       kind: TokenType::IncrementValue,
       lexeme: '+',
     }, Token {
-      pos: 1,
+      span: Span { line: 1, col: 2, byte_offset: 1 },
       kind: TokenType::EOF,
       lexeme: '\0',
     }]);
-    let ast = parser.parse();
+    let ast = parser.parse().unwrap();
     assert_eq!(ast, Ast {
       body: vec![Node {
         token: Token {
-          pos: 0,
+          span: Span { line: 1, col: 1, byte_offset: 0 },
           kind: TokenType::IncrementValue,
           lexeme: '+',
         },
@@ -370,19 +356,162 @@ mod tests {
   }
 
   #[test]
-  fn test_interpreter() {
-    let mut lexer = Lexer::new("++++++++++[>++++++++>+++++++++++>++++++++++>++++>+++>++++++++>++++++++++++>+++++++++++>++++++++++>+++++++++++>+++>+<<<<<<<<<<<<-]>-.>--.>---.>++++.>++.>---.>---.>.>.>+.>+++.>.\0");
+  fn test_ast_display_indents_by_loop_nesting() {
+    let mut lexer = Lexer::new("+[>-]");
     let tokens = lexer.tokenize();
     let mut parser = Parser::new(tokens);
-    let ast = parser.parse();
+    let ast = parser.parse().unwrap();
+    assert_eq!(
+      ast.to_string(),
+      "CellIncrement 1:1\nLoopStart 1:2\n  PointerIncrement 1:3\n  CellDecrement 1:4\n"
+    );
+  }
+
+  #[test]
+  fn test_compiler_and_vm() {
+    let mut lexer = Lexer::new("++++++++++[>++++++++>+++++++++++>++++++++++>++++>+++>++++++++>++++++++++++>+++++++++++>++++++++++>+++++++++++>+++>+<<<<<<<<<<<<-]>-.>--.>---.>++++.>++.>---.>---.>.>.>+.>+++.>.");
+    let tokens = lexer.tokenize();
+    let mut parser = Parser::new(tokens);
+    let ast = parser.parse().unwrap();
+    let program = Compiler::compile(&ast);
     let mut stdout: Vec<u8> = Vec::new();
-    let mut interpreter = Interpreter::new(ast, &mut stdout);
-    interpreter.interpret(None).unwrap();
+    let mut vm = Vm::new(program, &mut stdout);
+    vm.run().unwrap();
     assert_eq!(stdout.iter().map(|&c| c as char).collect::<String>(), "Ola, Mundo!\n".to_string());
   }
 
+  #[test]
+  fn test_vm_step_limit() {
+    let mut lexer = Lexer::new("+[]");
+    let tokens = lexer.tokenize();
+    let mut parser = Parser::new(tokens);
+    let ast = parser.parse().unwrap();
+    let program = Compiler::compile(&ast);
+    let mut stdout: Vec<u8> = Vec::new();
+    let limits = VmLimits {
+      max_steps: Some(10),
+      ..VmLimits::default()
+    };
+    let mut vm = Vm::with_limits(program, &mut stdout, limits);
+    assert!(vm.run().is_err());
+  }
+
+  #[test]
+  fn test_optimizer_clear_loop() {
+    let mut lexer = Lexer::new("+++++[-]");
+    let tokens = lexer.tokenize();
+    let mut parser = Parser::new(tokens);
+    let ast = parser.parse().unwrap();
+    let program = Compiler::compile(&ast);
+    let mut stdout: Vec<u8> = Vec::new();
+    let mut vm = Vm::new(program, &mut stdout);
+    vm.run().unwrap();
+    assert_eq!(vm.cells[0], 0);
+  }
+
+  #[test]
+  fn test_optimizer_multiply_loop() {
+    let mut lexer = Lexer::new("+++++[->+<]");
+    let tokens = lexer.tokenize();
+    let mut parser = Parser::new(tokens);
+    let ast = parser.parse().unwrap();
+    let program = Compiler::compile(&ast);
+    let mut stdout: Vec<u8> = Vec::new();
+    let mut vm = Vm::new(program, &mut stdout);
+    vm.run().unwrap();
+    assert_eq!(vm.cells[0], 0);
+    assert_eq!(vm.cells[1], 5);
+  }
+
   #[test]
   fn test_run() {
-    run("++++++++++[>++++++++>+++++++++++>++++++++++>++++>+++>++++++++>++++++++++++>+++++++++++>++++++++++>+++++++++++>+++>+<<<<<<<<<<<<-]>-.>--.>---.>++++.>++.>---.>---.>.>.>+.>+++.>.\0");
+    run("++++++++++[>++++++++>+++++++++++>++++++++++>++++>+++>++++++++>++++++++++++>+++++++++++>++++++++++>+++++++++++>+++>+<<<<<<<<<<<<-]>-.>--.>---.>++++.>++.>---.>---.>.>.>+.>+++.>.", "").unwrap();
+  }
+
+  #[test]
+  fn test_run_reads_provided_input() {
+    let output = run(",.\0", "A").unwrap();
+    assert_eq!(output, "A".to_string());
+  }
+
+  #[test]
+  fn test_vm_configures_tape_size_and_eof_behavior() {
+    let mut lexer = Lexer::new(",.");
+    let tokens = lexer.tokenize();
+    let mut parser = Parser::new(tokens);
+    let ast = parser.parse().unwrap();
+    let program = Compiler::compile(&ast);
+    let mut stdout: Vec<u8> = Vec::new();
+    let mut stdin: &[u8] = &[];
+    let mut vm = Vm::new(program, &mut stdout)
+      .with_tape_size(4)
+      .with_eof_behavior(EofBehavior::StoreZero)
+      .with_input(&mut stdin);
+    vm.run().unwrap();
+    assert_eq!(vm.cells.len(), 4);
+    assert_eq!(stdout, vec![0]);
+  }
+
+  #[test]
+  fn test_vm_wraps_pointer_on_overflow() {
+    let mut lexer = Lexer::new("<");
+    let tokens = lexer.tokenize();
+    let mut parser = Parser::new(tokens);
+    let ast = parser.parse().unwrap();
+    let program = Compiler::compile(&ast);
+    let mut stdout: Vec<u8> = Vec::new();
+    let mut vm = Vm::new(program, &mut stdout)
+      .with_tape_size(2)
+      .with_overflow(TapeOverflow::Wrap);
+    vm.run().unwrap();
+    assert_eq!(vm.pointer, 1);
+  }
+
+  #[test]
+  fn test_vm_grows_tape_on_overflow() {
+    let mut lexer = Lexer::new(">");
+    let tokens = lexer.tokenize();
+    let mut parser = Parser::new(tokens);
+    let ast = parser.parse().unwrap();
+    let program = Compiler::compile(&ast);
+    let mut stdout: Vec<u8> = Vec::new();
+    let mut vm = Vm::new(program, &mut stdout)
+      .with_tape_size(1)
+      .with_overflow(TapeOverflow::Grow);
+    vm.run().unwrap();
+    assert_eq!(vm.cells.len(), 2);
+    assert_eq!(vm.pointer, 1);
+  }
+
+  #[test]
+  fn test_vm_enforces_max_tape_growth() {
+    let mut lexer = Lexer::new(">>");
+    let tokens = lexer.tokenize();
+    let mut parser = Parser::new(tokens);
+    let ast = parser.parse().unwrap();
+    let program = Compiler::compile(&ast);
+    let mut stdout: Vec<u8> = Vec::new();
+    let limits = VmLimits {
+      max_tape_growth: Some(1),
+      ..VmLimits::default()
+    };
+    let mut vm = Vm::with_limits(program, &mut stdout, limits)
+      .with_tape_size(1)
+      .with_overflow(TapeOverflow::Grow);
+    assert!(vm.run().is_err());
+  }
+
+  #[test]
+  fn test_vm_errors_on_overflow() {
+    let mut lexer = Lexer::new(">");
+    let tokens = lexer.tokenize();
+    let mut parser = Parser::new(tokens);
+    let ast = parser.parse().unwrap();
+    let program = Compiler::compile(&ast);
+    let mut stdout: Vec<u8> = Vec::new();
+    let mut vm = Vm::new(program, &mut stdout)
+      .with_tape_size(1)
+      .with_overflow(TapeOverflow::Error);
+    assert!(vm.run().is_err());
   }
 }
\ No newline at end of file
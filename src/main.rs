@@ -2,19 +2,71 @@ mod lib;
 
 use std::{io};
 
-use lib::{Lexer, Parser, Interpreter};
+use lib::{Compiler, EofBehavior, Lexer, Parser, TapeOverflow, Vm};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let dump_tokens = args.iter().any(|arg| arg == "--dump-tokens");
+    let dump_ast = args.iter().any(|arg| arg == "--dump-ast");
+    let file_path = args.iter().find(|arg| !arg.starts_with("--")).expect("missing source file argument");
+
+    let tape_size = args.iter()
+        .find_map(|arg| arg.strip_prefix("--tape-size="))
+        .map(|value| value.parse().expect("--tape-size expects a number"));
+    let max_tape_growth = args.iter()
+        .find_map(|arg| arg.strip_prefix("--max-tape-growth="))
+        .map(|value| value.parse().expect("--max-tape-growth expects a number"));
+    let overflow = args.iter()
+        .find_map(|arg| arg.strip_prefix("--overflow="))
+        .map(|value| match value {
+            "wrap" => TapeOverflow::Wrap,
+            "grow" => TapeOverflow::Grow,
+            "error" => TapeOverflow::Error,
+            other => panic!("unknown --overflow value: {other} (expected wrap, grow or error)"),
+        });
+    let eof_behavior = args.iter()
+        .find_map(|arg| arg.strip_prefix("--eof-behavior="))
+        .map(|value| match value {
+            "zero" => EofBehavior::StoreZero,
+            "unchanged" => EofBehavior::Unchanged,
+            other => panic!("unknown --eof-behavior value: {other} (expected zero or unchanged)"),
+        });
 
-fn main() -> io::Result<()> {
     let complete_path = std::env::current_dir().unwrap();
-    let file_path = std::env::args().nth(1).unwrap();
     let input = std::fs::read_to_string(complete_path.join(file_path)).unwrap();
-    let sanitized_input = input.split("\n").into_iter().map(|line| line.trim()).collect::<Vec<&str>>().join("");
-    let mut lexer = Lexer::new(&sanitized_input);
+    let mut lexer = Lexer::new(&input);
     let tokens = lexer.tokenize();
+
+    if dump_tokens {
+        for token in tokens.iter() {
+            println!("{}:{} {:?} {:?}", token.span.line, token.span.col, token.kind, token.lexeme);
+        }
+        return Ok(());
+    }
+
     let mut parser = Parser::new(tokens);
-    let ast = parser.parse();
+    let ast = parser.parse()?;
+
+    if dump_ast {
+        print!("{}", ast);
+        return Ok(());
+    }
+
+    let program = Compiler::compile(&ast);
     let mut stdout = io::stdout();
-    let mut interpreter = Interpreter::new(ast, &mut stdout);
-    interpreter.interpret(None)?;
+    let mut vm = Vm::new(program, &mut stdout);
+    if let Some(tape_size) = tape_size {
+        vm = vm.with_tape_size(tape_size);
+    }
+    if let Some(overflow) = overflow {
+        vm = vm.with_overflow(overflow);
+    }
+    if let Some(eof_behavior) = eof_behavior {
+        vm = vm.with_eof_behavior(eof_behavior);
+    }
+    if let Some(max_tape_growth) = max_tape_growth {
+        vm = vm.with_max_tape_growth(max_tape_growth);
+    }
+    vm.run()?;
     Ok(())
 }
@@ -0,0 +1,202 @@
+use std::io::{self, Read};
+
+use super::compiler::Op;
+
+/// Bounds on a `Vm` run so a compiled program can't hang its caller (e.g. the
+/// WASM `run()` entry point) on an infinite loop or a runaway tape.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VmLimits {
+  pub max_steps: Option<u64>,
+  /// Caps how many cells `TapeOverflow::Grow` may add past the tape's
+  /// starting size; growing past the cap errors out the same as
+  /// `TapeOverflow::Error`.
+  pub max_tape_growth: Option<usize>,
+}
+
+/// What happens when the pointer moves past either end of the tape.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TapeOverflow {
+  /// Wrap around to the other end (the VM's original, and still default, behavior).
+  #[default]
+  Wrap,
+  /// Extend the tape with zeroed cells as the pointer moves past the end.
+  Grow,
+  /// Return an error instead of moving out of bounds.
+  Error,
+}
+
+/// What a cell becomes when `,` is executed and the input source is
+/// exhausted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EofBehavior {
+  StoreZero,
+  #[default]
+  Unchanged,
+}
+
+pub struct Vm<'a> {
+  pub code: Vec<Op>,
+  pub cells: Vec<u8>,
+  pub pointer: usize,
+  pub ip: usize,
+  pub output: &'a mut dyn io::Write,
+  pub input: Option<&'a mut dyn io::Read>,
+  pub overflow: TapeOverflow,
+  pub eof_behavior: EofBehavior,
+  pub limits: VmLimits,
+  initial_tape_size: usize,
+}
+
+impl<'a> Vm<'a> {
+  pub fn new(code: Vec<Op>, output: &'a mut dyn io::Write) -> Self {
+    Self::with_limits(code, output, VmLimits::default())
+  }
+
+  pub fn with_limits(code: Vec<Op>, output: &'a mut dyn io::Write, limits: VmLimits) -> Self {
+    let tape_size = 30_000;
+    Self {
+      code,
+      cells: vec![0; tape_size],
+      pointer: 0,
+      ip: 0,
+      output,
+      input: None,
+      overflow: TapeOverflow::default(),
+      eof_behavior: EofBehavior::default(),
+      limits,
+      initial_tape_size: tape_size,
+    }
+  }
+
+  /// Feeds `,` from `input` instead of `std::io::stdin()` — the WASM `run()`
+  /// entry point uses this to pipe in a caller-provided string.
+  pub fn with_input(mut self, input: &'a mut dyn io::Read) -> Self {
+    self.input = Some(input);
+    self
+  }
+
+  pub fn with_tape_size(mut self, tape_size: usize) -> Self {
+    self.cells = vec![0; tape_size];
+    self.initial_tape_size = tape_size;
+    self
+  }
+
+  pub fn with_overflow(mut self, overflow: TapeOverflow) -> Self {
+    self.overflow = overflow;
+    self
+  }
+
+  pub fn with_eof_behavior(mut self, eof_behavior: EofBehavior) -> Self {
+    self.eof_behavior = eof_behavior;
+    self
+  }
+
+  pub fn with_max_tape_growth(mut self, max_tape_growth: usize) -> Self {
+    self.limits.max_tape_growth = Some(max_tape_growth);
+    self
+  }
+
+  pub fn run(&mut self) -> io::Result<()> {
+    let mut steps: u64 = 0;
+
+    while self.ip < self.code.len() {
+      if let Some(max_steps) = self.limits.max_steps {
+        if steps >= max_steps {
+          return Err(io::Error::new(io::ErrorKind::TimedOut, "vm step limit exceeded"));
+        }
+      }
+
+      let mut next_ip = self.ip + 1;
+
+      match self.code[self.ip] {
+        Op::IncPtr(n) => self.move_pointer(n as isize)?,
+        Op::DecPtr(n) => self.move_pointer(-(n as isize))?,
+        Op::IncVal(n) => {
+          self.cells[self.pointer] = self.cells[self.pointer].wrapping_add(n);
+        },
+        Op::DecVal(n) => {
+          self.cells[self.pointer] = self.cells[self.pointer].wrapping_sub(n);
+        },
+        Op::Output => {
+          write!(self.output, "{}", self.cells[self.pointer] as char)?;
+        },
+        Op::Input => {
+          let mut input: [u8; 1] = [0];
+          let result = match &mut self.input {
+            Some(reader) => reader.read_exact(&mut input),
+            None => io::stdin().read_exact(&mut input),
+          };
+
+          match result {
+            Ok(()) => self.cells[self.pointer] = input[0],
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+              if let EofBehavior::StoreZero = self.eof_behavior {
+                self.cells[self.pointer] = 0;
+              }
+            },
+            Err(error) => return Err(error),
+          }
+        },
+        Op::JumpIfZero(target) => {
+          if self.cells[self.pointer] == 0 {
+            next_ip = target;
+          }
+        },
+        Op::JumpIfNonZero(target) => {
+          if self.cells[self.pointer] != 0 {
+            next_ip = target;
+          }
+        },
+        Op::SetVal(value) => {
+          self.cells[self.pointer] = value;
+        },
+        Op::MulAdd { offset, factor } => {
+          let target = self.offset_index(offset);
+          let product = (self.cells[self.pointer] as i32) * (factor as i32);
+          self.cells[target] = self.cells[target].wrapping_add(product as u8);
+        },
+      }
+
+      self.ip = next_ip;
+      steps += 1;
+    }
+
+    Ok(())
+  }
+
+  fn move_pointer(&mut self, delta: isize) -> io::Result<()> {
+    let len = self.cells.len() as isize;
+    let next = self.pointer as isize + delta;
+
+    if next >= 0 && next < len {
+      self.pointer = next as usize;
+      return Ok(());
+    }
+
+    match self.overflow {
+      TapeOverflow::Wrap => {
+        self.pointer = next.rem_euclid(len) as usize;
+        Ok(())
+      },
+      TapeOverflow::Grow if next >= len => {
+        let grown_by = next as usize + 1 - self.initial_tape_size;
+        if let Some(max_tape_growth) = self.limits.max_tape_growth {
+          if grown_by > max_tape_growth {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "tape growth limit exceeded"));
+          }
+        }
+        self.cells.resize(next as usize + 1, 0);
+        self.pointer = next as usize;
+        Ok(())
+      },
+      TapeOverflow::Grow | TapeOverflow::Error => {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "pointer moved out of tape bounds"))
+      },
+    }
+  }
+
+  fn offset_index(&self, offset: isize) -> usize {
+    let len = self.cells.len() as isize;
+    ((self.pointer as isize + offset).rem_euclid(len)) as usize
+  }
+}